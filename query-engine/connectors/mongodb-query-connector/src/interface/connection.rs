@@ -1,9 +1,8 @@
-use super::catch;
-use crate::{
-    error::MongoError,
-    root_queries::{aggregate, read, write},
-    MongoDbTransaction,
+use super::{
+    catch,
+    transaction::{isolation_level_to_options, run_in_transaction_with_retry, MongoDbTransaction},
 };
+use crate::root_queries::{aggregate, read, write};
 use async_trait::async_trait;
 use connector_interface::{
     Connection, ConnectionLike, ReadOperations, RelAggregationSelection, Transaction, UpdateType, WriteArgs,
@@ -23,20 +22,43 @@ pub struct MongoDbConnection {
 
 impl ConnectionLike for MongoDbConnection {}
 
+impl MongoDbConnection {
+    /// Executes a batch of heterogeneous writes as a single `bulkWrite`
+    /// command instead of one round-trip per write. `create_records` below
+    /// fuses its `InsertOne`s through this directly; also reachable as
+    /// [`MongoDbTransaction::bulk_write`] for call sites that need their
+    /// batch to participate in an already-open interactive transaction.
+    pub async fn bulk_write(
+        &mut self,
+        models: Vec<write::BulkWriteModel>,
+        ordered: bool,
+    ) -> connector_interface::Result<write::BulkWriteResult> {
+        catch(async move { write::bulk_write(&self.database, &mut self.session, models, ordered).await }).await
+    }
+
+    /// Runs `f` inside a MongoDB transaction, automatically retrying the
+    /// whole body (on a fresh attempt) or just the commit when the server
+    /// reports one of those as retryable. Prefer this over
+    /// [`Connection::start_transaction`] plus a one-shot commit whenever the
+    /// caller can re-invoke its transaction body, since a transient error on
+    /// a one-shot transaction otherwise bubbles up as a hard failure.
+    pub async fn start_transaction_with_retry<F, T>(&mut self, f: F) -> connector_interface::Result<T>
+    where
+        F: for<'a> FnMut(&'a mut MongoDbTransaction<'a>) -> futures::future::BoxFuture<'a, connector_interface::Result<T>>,
+    {
+        run_in_transaction_with_retry(self, None, f).await
+    }
+}
+
 #[async_trait]
 impl Connection for MongoDbConnection {
     async fn start_transaction<'a>(
         &'a mut self,
         isolation_level: Option<String>,
     ) -> connector_interface::Result<Box<dyn connector_interface::Transaction + 'a>> {
-        if isolation_level.is_some() {
-            return Err(MongoError::Unsupported(
-                "Mongo does not support setting transaction isolation levels.".to_owned(),
-            )
-            .into_connector_error());
-        }
+        let options = isolation_level.map(isolation_level_to_options).transpose()?;
 
-        let tx = Box::new(MongoDbTransaction::new(self).await?);
+        let tx = Box::new(MongoDbTransaction::new(self, options).await?);
 
         Ok(tx as Box<dyn Transaction>)
     }
@@ -64,10 +86,17 @@ impl WriteOperations for MongoDbConnection {
         skip_duplicates: bool,
         _trace_id: Option<String>,
     ) -> connector_interface::Result<usize> {
-        catch(
-            async move { write::create_records(&self.database, &mut self.session, model, args, skip_duplicates).await },
-        )
-        .await
+        let models = catch(async { write::insert_models_for_create(self.database.name(), model, args) }).await?;
+        let ordered = write::bulk_write_ordered_for_create(skip_duplicates);
+
+        // A single `bulkWrite` command is already atomic per document and
+        // covered by the driver's own retryable-writes support, so this must
+        // not wrap it in an explicit multi-statement transaction: that would
+        // fail outright against a standalone deployment and against a
+        // session that already has an interactive transaction open on it.
+        let result = self.bulk_write(models, ordered).await?;
+
+        Ok(result.inserted_count as usize)
     }
 
     async fn update_records(
@@ -163,10 +192,12 @@ impl WriteOperations for MongoDbConnection {
 
     async fn native_upsert_record(
         &mut self,
-        _upsert: connector_interface::NativeUpsert,
+        upsert: connector_interface::NativeUpsert,
         _trace_id: Option<String>,
     ) -> connector_interface::Result<SingleRecord> {
-        unimplemented!("Native upsert is not currently supported.")
+        let model = upsert.model().clone();
+
+        catch(async move { write::native_upsert_record(&self.database, &mut self.session, &model, upsert).await }).await
     }
 }
 
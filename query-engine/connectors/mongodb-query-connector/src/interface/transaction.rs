@@ -0,0 +1,213 @@
+use super::{catch, connection::MongoDbConnection};
+use async_trait::async_trait;
+use connector_interface::{ConnectionLike, Transaction};
+use futures::future::BoxFuture;
+use mongodb::options::{Acknowledgment, ReadConcern, TransactionOptions, WriteConcern};
+
+/// Max number of times the whole transaction body is re-run from scratch
+/// after a `TransientTransactionError`.
+const MAX_TRANSACTION_RETRIES: u32 = 4;
+/// Max number of times just `commitTransaction` is retried after an
+/// `UnknownTransactionCommitResult` (the commit may or may not have actually
+/// applied server-side, so only the commit itself is safe to retry here).
+const MAX_COMMIT_RETRIES: u32 = 4;
+const INITIAL_BACKOFF_MS: u64 = 20;
+
+pub struct MongoDbTransaction<'conn> {
+    connection: &'conn mut MongoDbConnection,
+}
+
+impl<'conn> MongoDbTransaction<'conn> {
+    pub async fn new(
+        connection: &'conn mut MongoDbConnection,
+        options: Option<TransactionOptions>,
+    ) -> connector_interface::Result<MongoDbTransaction<'conn>> {
+        catch(async {
+            connection
+                .session
+                .start_transaction()
+                .with_options(options)
+                .await
+                .map_err(crate::error::MongoError::from)
+        })
+        .await?;
+
+        Ok(Self { connection })
+    }
+
+    /// Delegates to [`MongoDbConnection::bulk_write`] so a transaction body
+    /// (e.g. one driven through [`run_in_transaction_with_retry`]) can fold
+    /// its writes into one `bulkWrite` without reaching back into the
+    /// connection directly.
+    pub async fn bulk_write(
+        &mut self,
+        models: Vec<crate::root_queries::write::BulkWriteModel>,
+        ordered: bool,
+    ) -> connector_interface::Result<crate::root_queries::write::BulkWriteResult> {
+        self.connection.bulk_write(models, ordered).await
+    }
+}
+
+#[async_trait]
+impl<'conn> Transaction for MongoDbTransaction<'conn> {
+    async fn commit(&mut self) -> connector_interface::Result<()> {
+        commit_with_retry(&mut self.connection.session).await
+    }
+
+    async fn rollback(&mut self) -> connector_interface::Result<()> {
+        catch(async {
+            self.connection
+                .session
+                .abort_transaction()
+                .await
+                .map_err(crate::error::MongoError::from)
+        })
+        .await
+    }
+
+    fn as_connection_like(&mut self) -> &mut dyn ConnectionLike {
+        self.connection.as_connection_like()
+    }
+}
+
+async fn commit_with_retry(session: &mut mongodb::ClientSession) -> connector_interface::Result<()> {
+    let mut attempt = 0;
+    let mut backoff_ms = INITIAL_BACKOFF_MS;
+
+    loop {
+        match session.commit_transaction().await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < MAX_COMMIT_RETRIES && err.contains_label("UnknownTransactionCommitResult") => {
+                attempt += 1;
+                tokio::time::sleep(std::time::Duration::from_millis(backoff_ms)).await;
+                backoff_ms *= 2;
+            }
+            Err(err) => return Err(crate::error::MongoError::from(err).into_connector_error()),
+        }
+    }
+}
+
+/// Runs `f` inside a fresh MongoDB transaction, retrying the whole body from
+/// scratch on a brand-new attempt when it — or the subsequent commit — fails
+/// with a `TransientTransactionError` (MongoDB's own guidance for
+/// transactions). Attempts are capped and anything not carrying the
+/// transient label (e.g. a duplicate key violation) is returned immediately.
+///
+/// `f` has to be callable more than once (once per attempt), and each call
+/// borrows a fresh [`MongoDbTransaction`] for only as long as that attempt's
+/// future runs — hence the boxed, higher-ranked signature instead of a
+/// single concrete `Future` type, which couldn't express a future borrowing
+/// its own `&mut MongoDbTransaction` argument.
+pub async fn run_in_transaction_with_retry<F, T>(
+    connection: &mut MongoDbConnection,
+    options: Option<TransactionOptions>,
+    mut f: F,
+) -> connector_interface::Result<T>
+where
+    F: for<'a> FnMut(&'a mut MongoDbTransaction<'a>) -> BoxFuture<'a, connector_interface::Result<T>>,
+{
+    let mut attempt = 0;
+    let mut backoff_ms = INITIAL_BACKOFF_MS;
+
+    loop {
+        let mut tx = MongoDbTransaction::new(&mut *connection, options.clone()).await?;
+
+        let body_result = f(&mut tx).await;
+
+        let value = match body_result {
+            Ok(value) => value,
+            Err(err) if attempt < MAX_TRANSACTION_RETRIES && is_transient_transaction_error(&err) => {
+                attempt += 1;
+                let _ = tx.rollback().await;
+                tokio::time::sleep(std::time::Duration::from_millis(backoff_ms)).await;
+                backoff_ms *= 2;
+                continue;
+            }
+            Err(err) => {
+                let _ = tx.rollback().await;
+                return Err(err);
+            }
+        };
+
+        match tx.commit().await {
+            Ok(()) => return Ok(value),
+            // `commit` already retries an `UnknownTransactionCommitResult`
+            // internally; a `TransientTransactionError` surfacing here means
+            // the whole transaction needs to be replayed, not just the
+            // commit, since the server may have aborted it entirely.
+            Err(err) if attempt < MAX_TRANSACTION_RETRIES && is_transient_transaction_error(&err) => {
+                attempt += 1;
+                tokio::time::sleep(std::time::Duration::from_millis(backoff_ms)).await;
+                backoff_ms *= 2;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// The driver labels transient errors on `mongodb::error::Error` itself, but
+/// by the time an error reaches the transaction-retry boundary it has
+/// already been converted to the generic `ConnectorError` the rest of the
+/// query engine deals in. Its `Display` does not carry driver labels through
+/// (they're metadata on the error, not part of its formatted message), so
+/// string-matching it never sees a real label — this walks the `source()`
+/// chain instead and asks the original driver error directly, the same way
+/// `commit_with_retry` above checks the label before any conversion happens.
+fn is_transient_transaction_error(err: &connector_interface::error::ConnectorError) -> bool {
+    driver_error(err)
+        .map(|driver_err| driver_err.contains_label("TransientTransactionError"))
+        .unwrap_or(false)
+}
+
+/// Walks `err`'s `source()` chain looking for the `mongodb::error::Error`
+/// a `MongoError` was originally built from.
+fn driver_error(err: &(dyn std::error::Error + 'static)) -> Option<&mongodb::error::Error> {
+    let mut source = err.source();
+
+    while let Some(err) = source {
+        if let Some(driver_err) = err.downcast_ref::<mongodb::error::Error>() {
+            return Some(driver_err);
+        }
+
+        source = err.source();
+    }
+
+    None
+}
+
+/// Maps a SQL-style isolation level onto the closest MongoDB
+/// `readConcern`/`writeConcern` pair, since Mongo has no `isolation_level`
+/// concept of its own:
+///
+/// - `Serializable`/`Snapshot` -> `readConcern: "snapshot"` (the strongest
+///   guarantee Mongo transactions offer)
+/// - `RepeatableRead`/`ReadCommitted` -> `readConcern: "majority"`
+/// - anything weaker (e.g. `ReadUncommitted`) -> `readConcern: "local"`
+///
+/// All of the above use `writeConcern: "majority"` so a committed write
+/// survives a primary step-down. Isolation levels we can't place anywhere on
+/// that scale are rejected, with the mapping spelled out in the error so
+/// callers know what Mongo can and cannot honor.
+pub fn isolation_level_to_options(isolation_level: String) -> connector_interface::Result<TransactionOptions> {
+    let write_concern = WriteConcern::builder().w(Acknowledgment::Majority).build();
+
+    let read_concern = match isolation_level.to_lowercase().as_str() {
+        "serializable" | "snapshot" => ReadConcern::snapshot(),
+        "repeatableread" | "readcommitted" => ReadConcern::majority(),
+        "readuncommitted" => ReadConcern::local(),
+        _ => {
+            return Err(crate::error::MongoError::Unsupported(format!(
+                "Mongo does not support the '{isolation_level}' isolation level. It can only honor: \
+                 Serializable/Snapshot (readConcern \"snapshot\"), RepeatableRead/ReadCommitted \
+                 (readConcern \"majority\"), and ReadUncommitted (readConcern \"local\"); all transactions \
+                 use writeConcern \"majority\"."
+            ))
+            .into_connector_error());
+        }
+    };
+
+    Ok(TransactionOptions::builder()
+        .read_concern(read_concern)
+        .write_concern(write_concern)
+        .build())
+}
@@ -0,0 +1,511 @@
+use crate::{error::MongoError, filter::convert_filter, root_queries::read::document_to_record, value::value_to_bson};
+use connector_interface::{NativeUpsert, WriteArgs, WriteOperation};
+use mongodb::{
+    action::bulk_write::WriteModel,
+    bson::{doc, Bson, Document},
+    error::ErrorKind,
+    options::{Acknowledgment, FindOneAndUpdateOptions, ReturnDocument, WriteConcern},
+    ClientSession, Database, Namespace,
+};
+use prisma_models::{Model, SingleRecord};
+use std::collections::HashMap;
+
+/// A single write to be executed as part of a batched `bulkWrite` command.
+///
+/// Each variant carries its own [`Namespace`], so a batch can fan out across
+/// several models (and therefore several collections) and still go to Mongo
+/// as one command.
+#[derive(Debug, Clone)]
+pub enum BulkWriteModel {
+    InsertOne {
+        namespace: Namespace,
+        document: Document,
+    },
+    UpdateOne {
+        namespace: Namespace,
+        filter: Document,
+        update: Document,
+    },
+    UpdateMany {
+        namespace: Namespace,
+        filter: Document,
+        update: Document,
+    },
+    ReplaceOne {
+        namespace: Namespace,
+        filter: Document,
+        replacement: Document,
+    },
+    DeleteOne {
+        namespace: Namespace,
+        filter: Document,
+    },
+    DeleteMany {
+        namespace: Namespace,
+        filter: Document,
+    },
+}
+
+impl BulkWriteModel {
+    fn into_driver_model(self) -> WriteModel {
+        match self {
+            Self::InsertOne { namespace, document } => WriteModel::InsertOne { namespace, document },
+            Self::UpdateOne {
+                namespace,
+                filter,
+                update,
+            } => WriteModel::UpdateOne {
+                namespace,
+                filter,
+                update: update.into(),
+                array_filters: None,
+                collation: None,
+                hint: None,
+                upsert: None,
+            },
+            Self::UpdateMany {
+                namespace,
+                filter,
+                update,
+            } => WriteModel::UpdateMany {
+                namespace,
+                filter,
+                update: update.into(),
+                array_filters: None,
+                collation: None,
+                hint: None,
+                upsert: None,
+            },
+            Self::ReplaceOne {
+                namespace,
+                filter,
+                replacement,
+            } => WriteModel::ReplaceOne {
+                namespace,
+                filter,
+                replacement,
+                collation: None,
+                hint: None,
+                upsert: None,
+            },
+            Self::DeleteOne { namespace, filter } => WriteModel::DeleteOne {
+                namespace,
+                filter,
+                collation: None,
+                hint: None,
+            },
+            Self::DeleteMany { namespace, filter } => WriteModel::DeleteMany {
+                namespace,
+                filter,
+                collation: None,
+                hint: None,
+            },
+        }
+    }
+}
+
+/// Aggregated outcome of a [`bulk_write`] call, broken down per operation
+/// type so a caller that fused e.g. three inserts and two updates into one
+/// batch can still tell them apart.
+#[derive(Debug, Default, Clone)]
+pub struct BulkWriteResult {
+    pub inserted_count: u64,
+    pub matched_count: u64,
+    pub modified_count: u64,
+    pub deleted_count: u64,
+    pub inserted_ids: HashMap<usize, Bson>,
+}
+
+impl From<mongodb::action::bulk_write::BulkWriteResult> for BulkWriteResult {
+    fn from(result: mongodb::action::bulk_write::BulkWriteResult) -> Self {
+        Self {
+            inserted_count: result.inserted_count,
+            matched_count: result.matched_count,
+            modified_count: result.modified_count,
+            deleted_count: result.deleted_count,
+            inserted_ids: result.inserted_ids,
+        }
+    }
+}
+
+/// Mongo's duplicate-key error code, reused below to tell "the unique index
+/// rejected this doc" apart from every other way a write can fail.
+const DUPLICATE_KEY_ERROR_CODE: i32 = 11000;
+
+/// Collapses a heterogeneous list of writes into a single `bulkWrite`
+/// round-trip instead of issuing `models.len()` independent commands.
+///
+/// When `ordered` is `true`, Mongo stops at the first failing op and the
+/// error is returned as-is. When `false`, Mongo keeps going and reports every
+/// failed op at once as a single `Err` carrying whatever did succeed; see
+/// [`recover_partial_result`] for how that partial success is unpacked.
+pub async fn bulk_write(
+    database: &Database,
+    session: &mut ClientSession,
+    models: Vec<BulkWriteModel>,
+    ordered: bool,
+) -> crate::error::Result<BulkWriteResult> {
+    let driver_models: Vec<_> = models.into_iter().map(BulkWriteModel::into_driver_model).collect();
+
+    let mut action = database.client().bulk_write(driver_models).ordered(ordered);
+
+    // A per-operation write concern can't be set while this session has a
+    // multi-statement transaction open — the transaction's own write concern
+    // governs instead, and the driver rejects the combination outright. Only
+    // set one here for a standalone `bulkWrite`.
+    if !session.in_transaction() {
+        action = action.write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build());
+    }
+
+    match action.session(&mut *session).await {
+        Ok(result) => Ok(result.into()),
+        Err(err) => recover_partial_result(err, ordered),
+    }
+}
+
+/// `bulkWrite` reports per-op failures as an `Err` even when `ordered` is
+/// `false` — unordered only means Mongo kept going past them, not that the
+/// driver swallows them. The failures (and whatever succeeded before/around
+/// them) come back on the error itself as `write_errors`/`partial_result`.
+///
+/// Unordered + "skip duplicates" is exactly "ignore duplicate-key failures,
+/// keep whatever wrote", so when every failed op in an unordered batch is a
+/// duplicate key, that's success, not an error. Anything else — a different
+/// failure code, or an ordered batch, which never has partial success to
+/// recover — still propagates as-is.
+fn recover_partial_result(err: mongodb::error::Error, ordered: bool) -> crate::error::Result<BulkWriteResult> {
+    let only_duplicate_keys = !ordered
+        && matches!(
+            &*err.kind,
+            ErrorKind::ClientBulkWrite(bulk_err)
+                if !bulk_err.write_errors.is_empty()
+                    && bulk_err.write_errors.values().all(|write_err| write_err.code == DUPLICATE_KEY_ERROR_CODE)
+        );
+
+    if !only_duplicate_keys {
+        return Err(MongoError::from(err));
+    }
+
+    let ErrorKind::ClientBulkWrite(bulk_err) = *err.kind else {
+        unreachable!("only_duplicate_keys only matches ErrorKind::ClientBulkWrite");
+    };
+
+    Ok(bulk_err.partial_result.map(BulkWriteResult::from).unwrap_or_default())
+}
+
+/// Flattens a create's [`WriteArgs`] into the plain document `bulkWrite`'s
+/// `InsertOne` model expects. Creates only ever carry plain `Set` values (a
+/// create has nothing to increment/multiply relative to), so anything else
+/// is a connector bug rather than something to translate.
+fn write_args_to_insert_document(args: WriteArgs) -> crate::error::Result<Document> {
+    let mut document = Document::new();
+
+    for (field, op) in args.args.into_iter() {
+        let WriteOperation::Scalar(scalar_op) = op else {
+            return Err(MongoError::Unsupported(format!(
+                "Create does not support composite write operations (field '{}').",
+                field.db_name()
+            )));
+        };
+
+        let value = scalar_op
+            .into_value()
+            .ok_or_else(|| MongoError::Unsupported(format!("Create requires a concrete value for field '{}'.", field.db_name())))?;
+
+        document.insert(field.db_name().to_owned(), value_to_bson(value)?);
+    }
+
+    Ok(document)
+}
+
+/// `create_records` maps `skip_duplicates` onto an *unordered* `bulkWrite`:
+/// unordered keeps inserting past a duplicate-key failure instead of
+/// aborting the rest of the batch, which is exactly "skip the duplicates,
+/// keep going".
+pub(crate) fn bulk_write_ordered_for_create(skip_duplicates: bool) -> bool {
+    !skip_duplicates
+}
+
+/// Builds the `InsertOne` batch for a `createMany`, one model per record, all
+/// targeting `model`'s namespace.
+pub fn insert_models_for_create(
+    database_name: &str,
+    model: &Model,
+    args: Vec<WriteArgs>,
+) -> crate::error::Result<Vec<BulkWriteModel>> {
+    let namespace = Namespace {
+        db: database_name.to_owned(),
+        coll: model.db_name().to_owned(),
+    };
+
+    args.into_iter()
+        .map(|args| {
+            write_args_to_insert_document(args).map(|document| BulkWriteModel::InsertOne {
+                namespace: namespace.clone(),
+                document,
+            })
+        })
+        .collect()
+}
+
+/// Translates one update-arg write operation into the Mongo update operator
+/// it belongs under (`$inc`/`$mul`/...), so atomic ops survive instead of
+/// being flattened into literal `$set` values.
+fn scalar_update_operator(op: connector_interface::ScalarWriteOperation) -> crate::error::Result<(&'static str, Bson)> {
+    use connector_interface::ScalarWriteOperation::*;
+
+    match op {
+        Set(value) => Ok(("$set", value_to_bson(value)?)),
+        Add(value) => Ok(("$inc", value_to_bson(value)?)),
+        Substract(value) => Ok(("$inc", negate_numeric(value_to_bson(value)?)?)),
+        Multiply(value) => Ok(("$mul", value_to_bson(value)?)),
+        // Mongo's classic update operators have no numeric `$divide` (only
+        // `$mul`), and approximating one via a `$mul` by the reciprocal is
+        // lossy: integer division gets truncated into a reciprocal double,
+        // silently coercing an Int32/Int64 field to Double. An aggregation
+        // pipeline update (`[{"$set": {field: {"$divide": [...]}}}]`) can
+        // express a real `$divide`, but that's a different update shape than
+        // every other operator here builds, so until a caller actually needs
+        // it, reject this rather than hand back a wrong value.
+        Divide(_) => Err(MongoError::Unsupported(
+            "Native upsert does not support division updates: Mongo has no numeric $divide update \
+             operator, and approximating it with a reciprocal $mul truncates integer fields and \
+             coerces them to Double."
+                .to_owned(),
+        )),
+        Field(_) => Err(MongoError::Unsupported(
+            "Native upsert does not support relation field writes in its update args.".to_owned(),
+        )),
+    }
+}
+
+fn negate_numeric(value: Bson) -> crate::error::Result<Bson> {
+    match value {
+        Bson::Int32(v) => Ok(Bson::Int32(-v)),
+        Bson::Int64(v) => Ok(Bson::Int64(-v)),
+        Bson::Double(v) => Ok(Bson::Double(-v)),
+        other => Err(MongoError::Unsupported(format!("Cannot decrement a non-numeric value ({other})."))),
+    }
+}
+
+/// Builds the `$set`/`$inc`/`$mul`/... document for an upsert's update args,
+/// grouping each field under the operator its write operation maps to.
+fn update_args_to_operator_doc(args: &WriteArgs) -> crate::error::Result<Document> {
+    let mut operators = Document::new();
+
+    for (field, op) in args.args.iter() {
+        let WriteOperation::Scalar(scalar_op) = op.clone() else {
+            return Err(MongoError::Unsupported(format!(
+                "Native upsert does not support composite write operations (field '{}').",
+                field.db_name()
+            )));
+        };
+
+        let (operator, value) = scalar_update_operator(scalar_op)?;
+        operators
+            .entry(operator.to_owned())
+            .or_insert_with(|| Bson::Document(Document::new()))
+            .as_document_mut()
+            .expect("operator bucket is always inserted as a document")
+            .insert(field.db_name().to_owned(), value);
+    }
+
+    Ok(operators)
+}
+
+/// Collects every field path a rendered filter document actually constrains,
+/// inserting them into `paths`.
+///
+/// A flat equality filter (`{"field": value}`) works with a top-level
+/// `filter.keys()` read, but `@@unique`/compound filters render through
+/// [`convert_filter`] as `{"$and": [{"field": value}, ...]}` instead of a
+/// flat map, so a top-level-only read misses every field inside the `$and`
+/// and lets it slip through to `$setOnInsert` — which then collides with
+/// what the filter/`$set` already touch. This walks into `$and`/`$or`
+/// clauses and skips operator keys (`$eq`, `$gt`, ...) so only real field
+/// paths end up in `paths`.
+fn filter_field_paths(filter: &Document, paths: &mut std::collections::HashSet<String>) {
+    for (key, value) in filter.iter() {
+        if key == "$and" || key == "$or" {
+            if let Bson::Array(clauses) = value {
+                for clause in clauses {
+                    if let Bson::Document(clause_doc) = clause {
+                        filter_field_paths(clause_doc, paths);
+                    }
+                }
+            }
+            continue;
+        }
+
+        if key.starts_with('$') {
+            continue;
+        }
+
+        paths.insert(key.to_owned());
+    }
+}
+
+/// Performs an upsert as a single atomic `findOneAndUpdate` with
+/// `upsert: true` instead of a read followed by a create-or-update, closing
+/// the race window the latter leaves open between the read and the write.
+pub async fn native_upsert_record(
+    database: &Database,
+    session: &mut ClientSession,
+    model: &Model,
+    upsert: NativeUpsert,
+) -> crate::error::Result<SingleRecord> {
+    let coll = database.collection::<Document>(model.db_name());
+
+    // The unique filter must be translated so Mongo can match it against an
+    // index (it's built from a `@unique`/`@@unique` field set), which is what
+    // makes the upsert atomic rather than a last-write-wins race.
+    let filter = convert_filter(upsert.filter().clone(), false, false)?.render();
+
+    // Fields the update args already touch, and fields the equality filter
+    // itself touches (Mongo inserts those into the new document on its own),
+    // must be kept out of `$setOnInsert` — putting the same path under both
+    // `$set`/the filter *and* `$setOnInsert` is a `ConflictingUpdateOperators`
+    // error, and the common upsert updates and creates overlapping fields.
+    let mut excluded_from_create: std::collections::HashSet<String> =
+        upsert.update().args.keys().map(|field| field.db_name().to_owned()).collect();
+    filter_field_paths(&filter, &mut excluded_from_create);
+
+    let mut update = update_args_to_operator_doc(upsert.update())?;
+
+    let mut set_on_insert_doc = Document::new();
+    for (field, op) in upsert.create().args.iter() {
+        let name = field.db_name().to_owned();
+
+        if excluded_from_create.contains(&name) {
+            continue;
+        }
+
+        let value = op
+            .clone()
+            .into_value()
+            .ok_or_else(|| MongoError::Unsupported(format!("Create requires a concrete value for field '{name}'.")))?;
+
+        set_on_insert_doc.insert(name, value_to_bson(value)?);
+    }
+
+    if !set_on_insert_doc.is_empty() {
+        update.insert("$setOnInsert", set_on_insert_doc);
+    }
+
+    if update.is_empty() {
+        return Err(MongoError::Unsupported(
+            "Native upsert requires at least one field to update or create.".to_owned(),
+        ));
+    }
+
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+
+    let document = coll
+        .find_one_and_update(filter, update)
+        .with_options(options)
+        .session(&mut *session)
+        .await
+        .map_err(MongoError::from)?
+        .ok_or_else(|| MongoError::Unsupported("Upsert did not return a document".to_owned()))?;
+
+    let field_names: Vec<_> = upsert.selected_fields().db_names().collect();
+    let record = document_to_record(document, &field_names, &upsert.selected_fields().into())?;
+
+    Ok(SingleRecord { record, field_names })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ns() -> Namespace {
+        Namespace {
+            db: "test".to_owned(),
+            coll: "coll".to_owned(),
+        }
+    }
+
+    #[test]
+    fn skip_duplicates_maps_to_an_unordered_batch() {
+        assert!(!bulk_write_ordered_for_create(true));
+        assert!(bulk_write_ordered_for_create(false));
+    }
+
+    #[test]
+    fn insert_one_round_trips_namespace_and_document() {
+        let document = doc! { "a": 1 };
+
+        let driver_model = BulkWriteModel::InsertOne {
+            namespace: ns(),
+            document: document.clone(),
+        }
+        .into_driver_model();
+
+        match driver_model {
+            WriteModel::InsertOne { namespace, document: doc } => {
+                assert_eq!(namespace, ns());
+                assert_eq!(doc, document);
+            }
+            other => panic!("expected InsertOne, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn delete_many_round_trips_namespace_and_filter() {
+        let filter = doc! { "a": { "$gt": 1 } };
+
+        let driver_model = BulkWriteModel::DeleteMany {
+            namespace: ns(),
+            filter: filter.clone(),
+        }
+        .into_driver_model();
+
+        match driver_model {
+            WriteModel::DeleteMany { namespace, filter: f, .. } => {
+                assert_eq!(namespace, ns());
+                assert_eq!(f, filter);
+            }
+            other => panic!("expected DeleteMany, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn negate_numeric_flips_the_sign_for_each_numeric_bson_type() {
+        assert_eq!(negate_numeric(Bson::Int32(5)).unwrap(), Bson::Int32(-5));
+        assert_eq!(negate_numeric(Bson::Int64(5)).unwrap(), Bson::Int64(-5));
+        assert_eq!(negate_numeric(Bson::Double(2.5)).unwrap(), Bson::Double(-2.5));
+    }
+
+    #[test]
+    fn negate_numeric_rejects_non_numeric_values() {
+        assert!(negate_numeric(Bson::String("nope".to_owned())).is_err());
+    }
+
+    #[test]
+    fn filter_field_paths_reads_flat_equality_filters() {
+        let filter = doc! { "id": 1 };
+
+        let mut paths = std::collections::HashSet::new();
+        filter_field_paths(&filter, &mut paths);
+
+        assert_eq!(paths, ["id".to_owned()].into_iter().collect());
+    }
+
+    #[test]
+    fn filter_field_paths_walks_into_and_clauses_and_unwraps_eq() {
+        let filter = doc! {
+            "$and": [
+                { "tenant_id": { "$eq": 1 } },
+                { "slug": "acme" },
+            ]
+        };
+
+        let mut paths = std::collections::HashSet::new();
+        filter_field_paths(&filter, &mut paths);
+
+        assert_eq!(paths, ["tenant_id".to_owned(), "slug".to_owned()].into_iter().collect());
+    }
+}